@@ -0,0 +1,87 @@
+use codegen;
+use prost_build;
+
+use names;
+use {comment_text, lower_name, method_path, path_matches, super_import};
+
+/// Generates the gRPC client bindings for a single service definition.
+pub struct ServiceGenerator;
+
+impl ServiceGenerator {
+    pub fn generate(&self,
+                     service: &prost_build::Service,
+                     scope: &mut codegen::Scope,
+                     attributes: &[(String, String)],
+                     extern_paths: &[(String, String)],
+                     compile_well_known_types: bool,
+                     disable_comments: &[String])
+    {
+        let fq_name = format!("{}.{}", service.package, service.proto_name);
+        let struct_name = format!("{}Client", service.name);
+
+        let structure = scope.new_struct(&struct_name)
+            .derive("Debug")
+            .derive("Clone")
+            .vis("pub")
+            .generic("T")
+            .field("inner", "::tower_grpc::client::Grpc<T>");
+
+        for (pattern, attribute) in attributes {
+            if path_matches(pattern, &fq_name) {
+                structure.attr(attribute);
+            }
+        }
+
+        if !disable_comments.iter().any(|p| path_matches(p, &fq_name)) {
+            if let Some(doc) = comment_text(&service.comments) {
+                structure.doc(&doc);
+            }
+        }
+
+        let implementation = scope.new_impl(&struct_name)
+            .generic("T")
+            .target_generic("T");
+
+        // Mirror any matching attribute onto the `impl` block too: an
+        // attribute that affects the struct's existence (e.g. a `cfg`) would
+        // otherwise leave an unconditional `impl` referencing a struct that
+        // may not exist.
+        for (pattern, attribute) in attributes {
+            if path_matches(pattern, &fq_name) {
+                implementation.attr(attribute);
+            }
+        }
+
+        implementation.new_fn("new")
+            .vis("pub")
+            .arg("inner", "T")
+            .ret("Self")
+            .line("let inner = ::tower_grpc::client::Grpc::new(inner);")
+            .line("Self { inner }");
+
+        for method in &service.methods {
+            let name = names::escape_ident(&lower_name(&method.name));
+            let path = method_path(service, method);
+            let method_fq_name = format!("{}.{}", fq_name, method.proto_name);
+
+            let input_type = super_import(&method.input_type, 0, scope, extern_paths, compile_well_known_types);
+            let output_type = super_import(&method.output_type, 0, scope, extern_paths, compile_well_known_types);
+
+            let func = implementation.new_fn(&name)
+                .vis("pub")
+                .arg_mut_self()
+                .arg("request", format!("::tower_grpc::Request<{}>", input_type))
+                .ret(format!(
+                    "::tower_grpc::client::unary::ResponseFuture<{}, T::Future, T::ResponseBody>",
+                    output_type))
+                .line(format!("let path = ::http::PathAndQuery::from_static({});", path))
+                .line("self.inner.unary(request, path)");
+
+            if !disable_comments.iter().any(|p| path_matches(p, &method_fq_name)) {
+                if let Some(doc) = comment_text(&method.comments) {
+                    func.doc(&doc);
+                }
+            }
+        }
+    }
+}
@@ -1,17 +1,24 @@
 extern crate codegen;
+extern crate prost;
 extern crate prost_build;
+extern crate prost_types;
 
 mod client;
 mod server;
 mod names;
 
+use std::env;
+use std::fs;
 use std::io;
 use std::cell::RefCell;
 use std::fmt::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
 use std::rc::Rc;
 use std::ascii::AsciiExt;
 
+use prost::Message;
+
 /// Code generation configuration
 pub struct Config {
     prost: prost_build::Config,
@@ -21,6 +28,37 @@ pub struct Config {
 struct Inner {
     build_client: bool,
     build_server: bool,
+
+    // (path_pattern, attribute) pairs to apply to generated client structs
+    // and server traits, respectively.
+    client_attributes: Vec<(String, String)>,
+    server_attributes: Vec<(String, String)>,
+
+    // Where to write the compiled `FileDescriptorSet`, for gRPC server
+    // reflection. `None` disables reflection support entirely.
+    file_descriptor_set_path: Option<PathBuf>,
+
+    // Whether the `DESCRIPTOR_SET` constant has already been emitted into
+    // the root scope for this build.
+    file_descriptor_set_emitted: bool,
+
+    // (proto_path, rust_path) mappings recording that types under
+    // `proto_path` (e.g. `.google.protobuf`) are defined externally, at
+    // `rust_path` (e.g. `::prost_types`), rather than generated in-tree.
+    extern_paths: Vec<(String, String)>,
+
+    // Whether `google.protobuf.*` well-known types should be compiled
+    // in-tree rather than treated as externally defined (in `::prost_types`).
+    compile_well_known_types: bool,
+
+    // Where generated code should be written, and the name of an
+    // include-aggregator file to emit there, if any.
+    out_dir: Option<PathBuf>,
+    include_file: Option<String>,
+
+    // path_patterns for which proto-derived doc comments should be omitted
+    // from generated items.
+    disable_comments: Vec<String>,
 }
 
 struct ServiceGenerator {
@@ -41,6 +79,21 @@ impl Config {
 
             // Disable server code gen by default
             build_server: false,
+
+            client_attributes: Vec::new(),
+            server_attributes: Vec::new(),
+
+            file_descriptor_set_path: None,
+            file_descriptor_set_emitted: false,
+
+            extern_paths: Vec::new(),
+
+            compile_well_known_types: false,
+
+            out_dir: None,
+            include_file: None,
+
+            disable_comments: Vec::new(),
         }));
 
         let root_scope = RefCell::new(codegen::Scope::new());
@@ -76,11 +129,248 @@ impl Config {
         self
     }
 
+    /// Add an extra attribute to apply to a generated client service struct.
+    ///
+    /// `path_pattern` is matched against the fully-qualified proto path of
+    /// the service (e.g. `"my.package.Service"`, or `"."` to match every
+    /// service) via prefix matching. `attribute` is emitted verbatim above
+    /// the generated struct, e.g. `"#[derive(PartialEq)]"` or
+    /// `"#[cfg(feature = \"grpc\")]"`.
+    pub fn client_attribute<P, A>(&mut self, path_pattern: P, attribute: A) -> &mut Self
+    where P: AsRef<str>,
+          A: AsRef<str>,
+    {
+        self.inner.borrow_mut().client_attributes.push((
+            path_pattern.as_ref().to_string(),
+            attribute.as_ref().to_string(),
+        ));
+        self
+    }
+
+    /// Add an extra attribute to apply to a generated server service trait.
+    ///
+    /// See [`client_attribute`](#method.client_attribute) for the matching
+    /// rules.
+    pub fn server_attribute<P, A>(&mut self, path_pattern: P, attribute: A) -> &mut Self
+    where P: AsRef<str>,
+          A: AsRef<str>,
+    {
+        self.inner.borrow_mut().server_attributes.push((
+            path_pattern.as_ref().to_string(),
+            attribute.as_ref().to_string(),
+        ));
+        self
+    }
+
+    /// Add an extra attribute to apply to a generated message type.
+    ///
+    /// Forwarded directly to the inner `prost_build::Config`.
+    pub fn type_attribute<P, A>(&mut self, path_pattern: P, attribute: A) -> &mut Self
+    where P: AsRef<str>,
+          A: AsRef<str>,
+    {
+        self.prost.type_attribute(path_pattern, attribute);
+        self
+    }
+
+    /// Add an extra attribute to apply to a generated message field.
+    ///
+    /// Forwarded directly to the inner `prost_build::Config`.
+    pub fn field_attribute<P, A>(&mut self, path_pattern: P, attribute: A) -> &mut Self
+    where P: AsRef<str>,
+          A: AsRef<str>,
+    {
+        self.prost.field_attribute(path_pattern, attribute);
+        self
+    }
+
+    /// Declare that types under the proto path `proto_path` (e.g.
+    /// `".google.protobuf"`) are already available at `rust_path` (e.g.
+    /// `"::prost_types"`), rather than being generated in-tree.
+    ///
+    /// This is forwarded to the inner `prost_build::Config` so message types
+    /// are remapped too, and also recorded so the client/server generators
+    /// emit the mapped absolute path instead of a `super::` chain when a
+    /// method's request or response type falls under `proto_path`.
+    pub fn extern_path<P, R>(&mut self, proto_path: P, rust_path: R) -> &mut Self
+    where P: AsRef<str>,
+          R: AsRef<str>,
+    {
+        self.prost.extern_path(proto_path.as_ref(), rust_path.as_ref());
+        self.inner.borrow_mut().extern_paths.push((
+            proto_path.as_ref().to_string(),
+            rust_path.as_ref().to_string(),
+        ));
+        self
+    }
+
+    /// Compile the protobuf well-known types (`google.protobuf.*`) in-tree,
+    /// rather than treating them as externally defined in `::prost_types`.
+    pub fn compile_well_known_types(&mut self, enable: bool) -> &mut Self {
+        self.prost.compile_well_known_types(enable);
+        self.inner.borrow_mut().compile_well_known_types = enable;
+        self
+    }
+
+    /// Set the output directory to write generated code to.
+    ///
+    /// Forwarded to the inner `prost_build::Config`; also recorded so
+    /// [`include_file`](#method.include_file) knows where to write the
+    /// include-aggregator file.
+    pub fn out_dir<P>(&mut self, path: P) -> &mut Self
+    where P: AsRef<Path>,
+    {
+        self.prost.out_dir(path.as_ref());
+        self.inner.borrow_mut().out_dir = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Emit a top-level file named `name` in the output directory that
+    /// `include!`s each generated package's module, so large proto trees
+    /// with many packages can be pulled in with a single `include!` at the
+    /// crate root.
+    ///
+    /// The file is only written when [`out_dir`](#method.out_dir) has also
+    /// been set; otherwise this setting has no effect.
+    pub fn include_file<S>(&mut self, name: S) -> &mut Self
+    where S: AsRef<str>,
+    {
+        self.inner.borrow_mut().include_file = Some(name.as_ref().to_string());
+        self
+    }
+
+    /// Suppress the proto-derived doc comments that would otherwise be
+    /// copied onto generated service/method items whose fully-qualified
+    /// name matches `path_pattern` (see
+    /// [`client_attribute`](#method.client_attribute) for the matching
+    /// rules).
+    pub fn disable_comments<P>(&mut self, path_pattern: P) -> &mut Self
+    where P: AsRef<str>,
+    {
+        self.inner.borrow_mut().disable_comments.push(path_pattern.as_ref().to_string());
+        self
+    }
+
+    /// Write the compiled `FileDescriptorSet` to `path`, and emit a
+    /// `DESCRIPTOR_SET` constant alongside the generated server code.
+    ///
+    /// This enables serving the gRPC Server Reflection protocol (e.g. for
+    /// use with `grpcurl`) against the generated service.
+    pub fn file_descriptor_set_path<P>(&mut self, path: P) -> &mut Self
+    where P: AsRef<Path>,
+    {
+        self.inner.borrow_mut().file_descriptor_set_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
     /// Generate code
     pub fn build<P>(&self, protos: &[P], includes: &[P]) -> io::Result<()>
     where P: AsRef<Path>,
     {
-        self.prost.compile_protos(protos, includes)
+        let (fds_path, out_dir, include_file) = {
+            let mut inner = self.inner.borrow_mut();
+            inner.file_descriptor_set_emitted = false;
+            (
+                inner.file_descriptor_set_path.clone(),
+                inner.out_dir.clone(),
+                inner.include_file.clone(),
+            )
+        };
+
+        // Both reflection support and package discovery for `include_file`
+        // need the compiled `FileDescriptorSet`; compute it at most once and
+        // share it between them, rather than invoking `protoc` twice.
+        let descriptor_set_bytes = if fds_path.is_some() || (out_dir.is_some() && include_file.is_some()) {
+            Some(self.compile_file_descriptor_set(protos, includes)?)
+        } else {
+            None
+        };
+
+        if let Some(ref fds_path) = fds_path {
+            fs::write(fds_path, descriptor_set_bytes.as_ref().unwrap())?;
+        }
+
+        self.prost.compile_protos(protos, includes)?;
+
+        if let (Some(out_dir), Some(include_file)) = (out_dir, include_file) {
+            let packages = Self::discover_packages(descriptor_set_bytes.as_ref().unwrap())?;
+            self.write_include_file(&out_dir, &include_file, &packages)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the set of proto packages defined in the decoded
+    /// `FileDescriptorSet` `bytes`, in the order protoc reports them.
+    ///
+    /// This is derived from the compiled `FileDescriptorSet` rather than
+    /// from the services `prost_build` generates, so packages that define
+    /// only messages (no RPC service) -- a common case for a shared "types"
+    /// package -- are still discovered.
+    fn discover_packages(bytes: &[u8]) -> io::Result<Vec<String>> {
+        let descriptor_set = prost_types::FileDescriptorSet::decode(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+        let mut packages = Vec::new();
+        for file in descriptor_set.file {
+            if let Some(package) = file.package {
+                if !package.is_empty() && !packages.contains(&package) {
+                    packages.push(package);
+                }
+            }
+        }
+
+        Ok(packages)
+    }
+
+    /// Writes `include_file` into `out_dir`, `include!`ing the generated
+    /// module for each package in `packages`.
+    fn write_include_file(&self, out_dir: &Path, include_file: &str, packages: &[String]) -> io::Result<()> {
+        let mut contents = String::new();
+
+        for package in packages {
+            writeln!(contents, "include!(\"{}.rs\");", package).expect("writing to a String cannot fail");
+        }
+
+        fs::write(out_dir.join(include_file), contents)
+    }
+
+    /// Invokes `protoc` to produce an encoded `FileDescriptorSet` for
+    /// `protos`, returning its raw bytes.
+    ///
+    /// Cargo may run multiple build scripts concurrently, so the
+    /// intermediate file protoc writes to is named uniquely per process
+    /// (rather than a single shared path in the temp dir) to avoid two
+    /// concurrent builds racing on it.
+    fn compile_file_descriptor_set<P>(&self, protos: &[P], includes: &[P]) -> io::Result<Vec<u8>>
+    where P: AsRef<Path>,
+    {
+        let tmp = env::temp_dir().join(format!("tower-grpc-file-descriptor-set-{}.bin", process::id()));
+
+        let protoc = env::var("PROTOC").unwrap_or_else(|_| "protoc".to_string());
+        let mut cmd = Command::new(protoc);
+        cmd.arg("--include_imports")
+            .arg("--include_source_info")
+            .arg("-o").arg(&tmp);
+
+        for include in includes {
+            cmd.arg("-I").arg(include.as_ref());
+        }
+        for proto in protos {
+            cmd.arg(proto.as_ref());
+        }
+
+        let status = cmd.status()?;
+        if !status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "protoc failed to generate file descriptor set",
+            ));
+        }
+
+        let bytes = fs::read(&tmp)?;
+        let _ = fs::remove_file(&tmp);
+        Ok(bytes)
     }
 }
 
@@ -92,15 +382,28 @@ impl prost_build::ServiceGenerator for ServiceGenerator {
         // `server::ServiceGenerator` will actually output any code to the
         // buffer; all code is written out in the implementation of the
         // `ServiceGenerator::finalize` function on this type.
-        let inner = self.inner.borrow();
+        let mut inner = self.inner.borrow_mut();
         let mut root = self.root_scope.borrow_mut();
 
         if inner.build_client {
-            self.client.generate(&service, &mut root);
+            self.client.generate(
+                &service, &mut root, &inner.client_attributes,
+                &inner.extern_paths, inner.compile_well_known_types,
+                &inner.disable_comments);
         }
 
         if inner.build_server {
-            self.server.generate(&service, &mut root);
+            self.server.generate(
+                &service, &mut root, &inner.server_attributes,
+                &inner.extern_paths, inner.compile_well_known_types,
+                &inner.disable_comments);
+
+            if !inner.file_descriptor_set_emitted {
+                if let Some(ref path) = inner.file_descriptor_set_path {
+                    server::emit_descriptor_set(&mut root, path);
+                    inner.file_descriptor_set_emitted = true;
+                }
+            }
         }
     }
 
@@ -155,7 +458,17 @@ fn lower_name(name: &str) -> String {
     ret
 }
 
-fn super_import(ty: &str, level: usize, scope: &mut codegen::Scope) -> String {
+fn super_import(
+    ty: &str,
+    level: usize,
+    scope: &mut codegen::Scope,
+    extern_paths: &[(String, String)],
+    compile_well_known_types: bool,
+) -> String {
+    if let Some(path) = extern_type_path(ty, extern_paths, compile_well_known_types) {
+        return path;
+    }
+
     let mut v: Vec<&str> = ty.split("::").collect();
     for _ in 0..level {
         v.insert(0, "super");
@@ -191,3 +504,116 @@ fn super_import(ty: &str, level: usize, scope: &mut codegen::Scope) -> String {
 fn unqualified(ty: &str) -> &str {
     ty.rsplit("::").next().unwrap_or(ty)
 }
+
+/// If `ty` was already resolved by `prost_build` to a path under one of
+/// `extern_paths`'s `rust_path`s, returns `ty` unchanged so callers leave it
+/// alone rather than mistakenly computing a `super::` chain for it.
+///
+/// `Config::extern_path` forwards straight through to the inner
+/// `prost_build::Config`, so by the time `ty` (a `Method::input_type` or
+/// `output_type`) reaches this function, prost has *already* rewritten it
+/// to the mapped absolute Rust path -- it is no longer the proto-dotted
+/// name `extern_path` was registered under, so matching must be done
+/// against `ty` itself, not by re-deriving a proto path from it. The same
+/// is true of `google.protobuf.*` types resolving to `::prost_types` unless
+/// `compile_well_known_types` is set.
+fn extern_type_path(
+    ty: &str,
+    extern_paths: &[(String, String)],
+    compile_well_known_types: bool,
+) -> Option<String> {
+    let is_under = |rust_path: &str| ty == rust_path || ty.starts_with(&format!("{}::", rust_path));
+
+    for (_, rust_path) in extern_paths {
+        if is_under(rust_path) {
+            return Some(ty.to_string());
+        }
+    }
+
+    if !compile_well_known_types && is_under("::prost_types") {
+        return Some(ty.to_string());
+    }
+
+    None
+}
+
+/// Returns whether `path_pattern` matches the fully-qualified proto name
+/// `fq_name`, via prefix matching. `"."` matches everything.
+fn path_matches(path_pattern: &str, fq_name: &str) -> bool {
+    path_pattern == "." || fq_name.starts_with(path_pattern)
+}
+
+/// Joins a proto item's leading comment lines into a single doc comment
+/// body, or returns `None` if it has none.
+fn comment_text(comments: &prost_build::Comments) -> Option<String> {
+    if comments.leading.is_empty() {
+        None
+    } else {
+        Some(comments.leading.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_matches_dot_matches_everything() {
+        assert!(path_matches(".", "my.package.Service"));
+        assert!(path_matches(".", ""));
+    }
+
+    #[test]
+    fn path_matches_is_a_prefix_match() {
+        assert!(path_matches("my.package", "my.package.Service"));
+        assert!(path_matches("my.package.Service", "my.package.Service"));
+        assert!(!path_matches("my.package.Service", "my.package"));
+        assert!(!path_matches("other.package", "my.package.Service"));
+    }
+
+    #[test]
+    fn extern_type_path_passes_through_an_already_resolved_type() {
+        let extern_paths = vec![(".x".to_string(), "::a::b".to_string())];
+
+        assert_eq!(
+            extern_type_path("::a::b::Foo", &extern_paths, false),
+            Some("::a::b::Foo".to_string()));
+        assert_eq!(
+            extern_type_path("::other::Foo", &extern_paths, false),
+            None);
+    }
+
+    #[test]
+    fn extern_type_path_well_known_types_depend_on_compile_well_known_types() {
+        assert_eq!(
+            extern_type_path("::prost_types::Timestamp", &[], false),
+            Some("::prost_types::Timestamp".to_string()));
+        assert_eq!(
+            extern_type_path("::prost_types::Timestamp", &[], true),
+            None);
+    }
+
+    #[test]
+    fn write_include_file_includes_every_package() {
+        let dir = env::temp_dir().join(format!("tower-grpc-build-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = Config::new();
+        let packages = vec!["foo".to_string(), "foo.bar".to_string()];
+        config.write_include_file(&dir, "mod.rs", &packages).unwrap();
+
+        let contents = fs::read_to_string(dir.join("mod.rs")).unwrap();
+        assert_eq!(contents, "include!(\"foo.rs\");\ninclude!(\"foo.bar.rs\");\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn comment_text_joins_leading_lines() {
+        let mut comments = prost_build::Comments::default();
+        comments.leading = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(comment_text(&comments), Some("a\nb".to_string()));
+
+        assert_eq!(comment_text(&prost_build::Comments::default()), None);
+    }
+}
@@ -0,0 +1,19 @@
+// Rust reserved keywords that can't be used verbatim as identifiers in
+// generated code (method names, field names, etc.).
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+    "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while",
+];
+
+/// Appends a trailing underscore to `name` if it collides with a Rust
+/// keyword, so it's safe to emit as a generated identifier.
+pub fn escape_ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
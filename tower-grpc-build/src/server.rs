@@ -0,0 +1,74 @@
+use std::env;
+use std::path::Path;
+
+use codegen;
+use prost_build;
+
+use names;
+use {comment_text, lower_name, path_matches, super_import};
+
+/// Generates the gRPC server trait for a single service definition.
+pub struct ServiceGenerator;
+
+impl ServiceGenerator {
+    pub fn generate(&self,
+                     service: &prost_build::Service,
+                     scope: &mut codegen::Scope,
+                     attributes: &[(String, String)],
+                     extern_paths: &[(String, String)],
+                     compile_well_known_types: bool,
+                     disable_comments: &[String])
+    {
+        let fq_name = format!("{}.{}", service.package, service.proto_name);
+
+        let trait_ = scope.new_trait(&service.name)
+            .vis("pub");
+
+        for (pattern, attribute) in attributes {
+            if path_matches(pattern, &fq_name) {
+                trait_.attr(attribute);
+            }
+        }
+
+        if !disable_comments.iter().any(|p| path_matches(p, &fq_name)) {
+            if let Some(doc) = comment_text(&service.comments) {
+                trait_.doc(&doc);
+            }
+        }
+
+        for method in &service.methods {
+            let name = names::escape_ident(&lower_name(&method.name));
+            let method_fq_name = format!("{}.{}", fq_name, method.proto_name);
+
+            let input_type = super_import(&method.input_type, 0, scope, extern_paths, compile_well_known_types);
+            let output_type = super_import(&method.output_type, 0, scope, extern_paths, compile_well_known_types);
+
+            let func = trait_.new_fn(&name)
+                .arg_mut_self()
+                .arg("request", format!("::tower_grpc::Request<{}>", input_type))
+                .ret(format!("::tower_grpc::server::unary::ResponseFuture<{}>", output_type));
+
+            if !disable_comments.iter().any(|p| path_matches(p, &method_fq_name)) {
+                if let Some(doc) = comment_text(&method.comments) {
+                    func.doc(&doc);
+                }
+            }
+        }
+    }
+}
+
+/// Emits a `DESCRIPTOR_SET` constant holding the encoded `FileDescriptorSet`
+/// written to `path`, so it can be handed to a gRPC Server Reflection
+/// service implementation at runtime.
+pub fn emit_descriptor_set(scope: &mut codegen::Scope, path: &Path) {
+    // `include_bytes!` resolves a relative path against the generated file,
+    // not the directory the build script wrote the descriptor set from.
+    let path = env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    scope.raw(&format!(
+        "/// The encoded `FileDescriptorSet` for this build, for use with a\n\
+         /// gRPC Server Reflection service implementation.\n\
+         pub const DESCRIPTOR_SET: &'static [u8] = include_bytes!({:?});",
+        path));
+}